@@ -0,0 +1,145 @@
+//! An `AsyncEncode` adapter that coalesces small writes into larger ones.
+
+use std::pin::Pin;
+
+use futures_core::task::{Context, Poll};
+use futures_io::AsyncWrite;
+
+use crate::{AsyncEncode, PollEnc};
+
+/// Wraps an encoder `E`, accumulating its output into an internal buffer and only calling
+/// `writer.poll_write` once the buffer holds at least `backpressure_boundary` bytes (or once `E`
+/// is fully encoded).
+///
+/// This exists because `AsyncEncode::poll_encode` calls `poll_write` exactly once per
+/// invocation: an encoder that emits many tiny chunks would otherwise perform one syscall per
+/// chunk. `Buffered` lets such an encoder write into memory cheaply and coalesces the result
+/// into fewer, larger `poll_write` calls.
+pub struct Buffered<E> {
+    backpressure_boundary: usize,
+    buf: Vec<u8>,
+    /// How much of `buf` has already been written to the writer.
+    flushed: usize,
+    inner: Option<E>,
+}
+
+impl<E> Buffered<E> {
+    /// Wrap `inner`, flushing to the writer once the buffer reaches `backpressure_boundary`
+    /// bytes.
+    pub fn new(inner: E, backpressure_boundary: usize) -> Buffered<E> {
+        Buffered::with_capacity(inner, backpressure_boundary, backpressure_boundary)
+    }
+
+    /// Like `new`, but pre-allocates `capacity` bytes for the internal buffer.
+    pub fn with_capacity(inner: E, backpressure_boundary: usize, capacity: usize) -> Buffered<E> {
+        Buffered {
+            backpressure_boundary,
+            buf: Vec::with_capacity(capacity),
+            flushed: 0,
+            inner: Some(inner),
+        }
+    }
+}
+
+struct VecWriter<'a>(&'a mut Vec<u8>);
+
+impl<'a> AsyncWrite for VecWriter<'a> {
+    fn poll_write(self: Pin<&mut Self>,
+                  _cx: &mut Context,
+                  buf: &[u8])
+                  -> Poll<Result<usize, futures_io::Error>> {
+        self.get_mut().0.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), futures_io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), futures_io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<E> Buffered<E>
+    where E: AsyncEncode
+{
+    // Writes `self.buf[self.flushed..]` to `writer` with one `poll_write`, clearing the buffer
+    // once all of it has been written. Returns the number of bytes written (0 if there was
+    // nothing to flush), or `Poll::Pending` if the writer is pending.
+    fn drain<W: AsyncWrite + Unpin>(&mut self, cx: &mut Context, writer: &mut W) -> Poll<Result<usize, futures_io::Error>> {
+        if self.flushed == self.buf.len() {
+            return Poll::Ready(Ok(0));
+        }
+
+        match Pin::new(&mut *writer).poll_write(cx, &self.buf[self.flushed..]) {
+            Poll::Ready(Ok(0)) => {
+                Poll::Ready(Err(futures_io::Error::new(futures_io::ErrorKind::WriteZero,
+                                                        "buffered writer returned 0 bytes")))
+            }
+            Poll::Ready(Ok(n)) => {
+                self.flushed += n;
+                if self.flushed == self.buf.len() {
+                    self.buf.clear();
+                    self.flushed = 0;
+                }
+                Poll::Ready(Ok(n))
+            }
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl<E> AsyncEncode for Buffered<E>
+    where E: AsyncEncode
+{
+    fn poll_encode<W: AsyncWrite + Unpin>(mut self, cx: &mut Context, writer: &mut W) -> PollEnc<Self> {
+        // The byte count this returns must be how many bytes actually reached `writer`, i.e.
+        // exactly what `self.drain` reports - never how many bytes `inner` wrote into the
+        // in-memory buffer, or those bytes would be counted once here and again later when
+        // `drain` really flushes them. So loop, buffering `inner`'s output without reporting
+        // any of it, until there is something to honestly report: either `inner` is done and
+        // drained, or the buffer has crossed `backpressure_boundary` and got (partially)
+        // drained, or the real writer is pending.
+        loop {
+            if self.buf.len() - self.flushed >= self.backpressure_boundary {
+                return match self.drain(cx, writer) {
+                    Poll::Ready(Ok(n)) => PollEnc::Progress(self, n),
+                    Poll::Pending => PollEnc::Pending(self),
+                    Poll::Ready(Err(err)) => PollEnc::Errored(err),
+                };
+            }
+
+            match self.inner.take() {
+                None => {
+                    // The inner encoder is done; only the buffer remains to be flushed,
+                    // regardless of whether it has reached the boundary.
+                    return match self.drain(cx, writer) {
+                        Poll::Ready(Ok(0)) => PollEnc::Done(0),
+                        Poll::Ready(Ok(n)) => PollEnc::Progress(self, n),
+                        Poll::Pending => PollEnc::Pending(self),
+                        Poll::Ready(Err(err)) => PollEnc::Errored(err),
+                    };
+                }
+                Some(inner) => {
+                    match inner.poll_encode(cx, &mut VecWriter(&mut self.buf)) {
+                        PollEnc::Done(_) => {
+                            self.inner = None;
+                            continue;
+                        }
+                        PollEnc::Progress(inner, _) => {
+                            self.inner = Some(inner);
+                            continue;
+                        }
+                        PollEnc::Pending(inner) => {
+                            self.inner = Some(inner);
+                            return PollEnc::Pending(self);
+                        }
+                        PollEnc::Errored(err) => return PollEnc::Errored(err),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Sequential composition of two encoders over the same writer.
+
+use futures_core::task::Context;
+use futures_io::AsyncWrite;
+
+use crate::{AsyncEncode, AsyncEncodeLen, PollEnc};
+
+enum State<A, B> {
+    First(A, B),
+    Second(B),
+}
+
+/// An encoder that first fully encodes `A`, then fully encodes `B`, over the same writer.
+/// Useful for serializing structured records such as a header followed by a body.
+pub struct Chain<A, B> {
+    state: State<A, B>,
+}
+
+impl<A, B> Chain<A, B> {
+    /// Create a `Chain` that encodes `a`, then `b`.
+    pub fn new(a: A, b: B) -> Chain<A, B> {
+        Chain { state: State::First(a, b) }
+    }
+}
+
+impl<A, B> AsyncEncode for Chain<A, B>
+    where A: AsyncEncode,
+          B: AsyncEncode
+{
+    fn poll_encode<W: AsyncWrite + Unpin>(self, cx: &mut Context, writer: &mut W) -> PollEnc<Self> {
+        match self.state {
+            State::First(a, b) => {
+                match a.poll_encode(cx, writer) {
+                    PollEnc::Done(n) => {
+                        PollEnc::Progress(Chain { state: State::Second(b) }, n)
+                    }
+                    PollEnc::Progress(a, n) => {
+                        PollEnc::Progress(Chain { state: State::First(a, b) }, n)
+                    }
+                    PollEnc::Pending(a) => {
+                        PollEnc::Pending(Chain { state: State::First(a, b) })
+                    }
+                    PollEnc::Errored(err) => PollEnc::Errored(err),
+                }
+            }
+            State::Second(b) => {
+                match b.poll_encode(cx, writer) {
+                    PollEnc::Done(n) => PollEnc::Done(n),
+                    PollEnc::Progress(b, n) => {
+                        PollEnc::Progress(Chain { state: State::Second(b) }, n)
+                    }
+                    PollEnc::Pending(b) => PollEnc::Pending(Chain { state: State::Second(b) }),
+                    PollEnc::Errored(err) => PollEnc::Errored(err),
+                }
+            }
+        }
+    }
+}
+
+impl<A, B> AsyncEncodeLen for Chain<A, B>
+    where A: AsyncEncodeLen,
+          B: AsyncEncodeLen
+{
+    fn remaining_bytes(&self) -> usize {
+        match &self.state {
+            State::First(a, b) => a.remaining_bytes() + b.remaining_bytes(),
+            State::Second(b) => b.remaining_bytes(),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Functorial combinators for building codecs out of other codecs.
+
+use futures_core::task::Context;
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::{AsyncDecode, AsyncEncode, DecodeError, PollDec, PollEnc};
+
+/// A decoder that transforms the item of a decoder `D` by applying `F` to it. See
+/// `AsyncDecode::map`.
+pub struct Map<D, F> {
+    inner: D,
+    f: F,
+}
+
+impl<D, F> Map<D, F> {
+    /// Create a `Map`, applying `f` to the item produced by `inner`.
+    pub fn new(inner: D, f: F) -> Map<D, F> {
+        Map { inner, f }
+    }
+}
+
+impl<D, F, U> AsyncDecode for Map<D, F>
+    where D: AsyncDecode,
+          F: FnOnce(D::Item) -> U
+{
+    type Item = U;
+    type Error = D::Error;
+
+    fn poll_decode<R: AsyncRead + Unpin>(self,
+                                 cx: &mut Context,
+                                 reader: &mut R)
+                                 -> PollDec<Self::Item, Self, Self::Error> {
+        let Map { inner, f } = self;
+
+        match inner.poll_decode(cx, reader) {
+            PollDec::Done(item, n) => PollDec::Done(f(item), n),
+            PollDec::Progress(inner, n) => PollDec::Progress(Map { inner, f }, n),
+            PollDec::Pending(inner) => PollDec::Pending(Map { inner, f }),
+            PollDec::Errored(err) => PollDec::Errored(err),
+        }
+    }
+}
+
+/// A decoder that transforms the `DataError` of a decoder `D` by applying `G` to it. See
+/// `AsyncDecode::map_err`.
+pub struct MapErr<D, G> {
+    inner: D,
+    g: G,
+}
+
+impl<D, G> MapErr<D, G> {
+    /// Create a `MapErr`, applying `g` to the `DataError` produced by `inner`.
+    pub fn new(inner: D, g: G) -> MapErr<D, G> {
+        MapErr { inner, g }
+    }
+}
+
+impl<D, G, U> AsyncDecode for MapErr<D, G>
+    where D: AsyncDecode,
+          G: FnOnce(D::Error) -> U
+{
+    type Item = D::Item;
+    type Error = U;
+
+    fn poll_decode<R: AsyncRead + Unpin>(self,
+                                 cx: &mut Context,
+                                 reader: &mut R)
+                                 -> PollDec<Self::Item, Self, Self::Error> {
+        let MapErr { inner, g } = self;
+
+        match inner.poll_decode(cx, reader) {
+            PollDec::Done(item, n) => PollDec::Done(item, n),
+            PollDec::Progress(inner, n) => PollDec::Progress(MapErr { inner, g }, n),
+            PollDec::Pending(inner) => PollDec::Pending(MapErr { inner, g }),
+            PollDec::Errored(DecodeError::ReaderError(err)) => {
+                PollDec::Errored(DecodeError::ReaderError(err))
+            }
+            PollDec::Errored(DecodeError::DataError(err)) => {
+                PollDec::Errored(DecodeError::DataError(g(err)))
+            }
+        }
+    }
+}
+
+enum AndThenState<D, F, D2> {
+    First(D, F),
+    Second(D2),
+}
+
+/// A decoder that runs a second decoder, obtained from the first decoder's item via `F`, over
+/// the same reader. See `AsyncDecode::and_then`.
+pub struct AndThen<D, F, D2> {
+    state: AndThenState<D, F, D2>,
+}
+
+impl<D, F, D2> AndThen<D, F, D2> {
+    /// Create an `AndThen`, running `inner` to completion and then using `f` to build a second
+    /// decoder from its item.
+    pub fn new(inner: D, f: F) -> AndThen<D, F, D2> {
+        AndThen { state: AndThenState::First(inner, f) }
+    }
+}
+
+impl<D, F, D2> AsyncDecode for AndThen<D, F, D2>
+    where D: AsyncDecode,
+          F: FnOnce(D::Item) -> D2,
+          D2: AsyncDecode<Error = D::Error>
+{
+    type Item = D2::Item;
+    type Error = D::Error;
+
+    fn poll_decode<R: AsyncRead + Unpin>(self,
+                                 cx: &mut Context,
+                                 reader: &mut R)
+                                 -> PollDec<Self::Item, Self, Self::Error> {
+        match self.state {
+            AndThenState::First(inner, f) => {
+                match inner.poll_decode(cx, reader) {
+                    PollDec::Done(item, n) => {
+                        let next = f(item);
+                        match next.poll_decode(cx, reader) {
+                            PollDec::Done(item, n2) => PollDec::Done(item, n + n2),
+                            PollDec::Progress(next, n2) => {
+                                PollDec::Progress(AndThen { state: AndThenState::Second(next) },
+                                                   n + n2)
+                            }
+                            PollDec::Pending(next) if n > 0 => {
+                                PollDec::Progress(AndThen { state: AndThenState::Second(next) }, n)
+                            }
+                            PollDec::Pending(next) => {
+                                PollDec::Pending(AndThen { state: AndThenState::Second(next) })
+                            }
+                            PollDec::Errored(err) => PollDec::Errored(err),
+                        }
+                    }
+                    PollDec::Progress(inner, n) => {
+                        PollDec::Progress(AndThen { state: AndThenState::First(inner, f) }, n)
+                    }
+                    PollDec::Pending(inner) => {
+                        PollDec::Pending(AndThen { state: AndThenState::First(inner, f) })
+                    }
+                    PollDec::Errored(err) => PollDec::Errored(err),
+                }
+            }
+            AndThenState::Second(inner) => {
+                match inner.poll_decode(cx, reader) {
+                    PollDec::Done(item, n) => PollDec::Done(item, n),
+                    PollDec::Progress(inner, n) => {
+                        PollDec::Progress(AndThen { state: AndThenState::Second(inner) }, n)
+                    }
+                    PollDec::Pending(inner) => {
+                        PollDec::Pending(AndThen { state: AndThenState::Second(inner) })
+                    }
+                    PollDec::Errored(err) => PollDec::Errored(err),
+                }
+            }
+        }
+    }
+}
+
+enum EncodeMapState<T, F, E> {
+    Unbuilt(T, F),
+    Building(E),
+}
+
+/// An encoder that builds its inner encoder `E` from a value `T` by applying `F` on the first
+/// call to `poll_encode`, the contravariant counterpart to `AsyncDecode::map`: rather than
+/// transforming a decoded item, it transforms the value to be encoded before encoding starts.
+/// See `encode_map`.
+pub struct EncodeMap<T, F, E> {
+    state: EncodeMapState<T, F, E>,
+}
+
+impl<T, F, E> EncodeMap<T, F, E>
+    where F: FnOnce(T) -> E
+{
+    /// Create an `EncodeMap` that builds the encoder for `item` by applying `f` to it.
+    pub fn new(item: T, f: F) -> EncodeMap<T, F, E> {
+        EncodeMap { state: EncodeMapState::Unbuilt(item, f) }
+    }
+}
+
+/// Build an encoder for `item` by applying `f` to it first, the contravariant counterpart to
+/// `AsyncDecode::map`: rather than transforming a decoded item after the fact, it transforms the
+/// value to be encoded before encoding starts.
+pub fn encode_map<T, F, E>(item: T, f: F) -> EncodeMap<T, F, E>
+    where F: FnOnce(T) -> E
+{
+    EncodeMap::new(item, f)
+}
+
+impl<T, F, E> AsyncEncode for EncodeMap<T, F, E>
+    where F: FnOnce(T) -> E,
+          E: AsyncEncode
+{
+    fn poll_encode<W: AsyncWrite + Unpin>(self, cx: &mut Context, writer: &mut W) -> PollEnc<Self> {
+        let inner = match self.state {
+            EncodeMapState::Unbuilt(item, f) => f(item),
+            EncodeMapState::Building(inner) => inner,
+        };
+
+        match inner.poll_encode(cx, writer) {
+            PollEnc::Done(n) => PollEnc::Done(n),
+            PollEnc::Progress(inner, n) => {
+                PollEnc::Progress(EncodeMap { state: EncodeMapState::Building(inner) }, n)
+            }
+            PollEnc::Pending(inner) => {
+                PollEnc::Pending(EncodeMap { state: EncodeMapState::Building(inner) })
+            }
+            PollEnc::Errored(err) => PollEnc::Errored(err),
+        }
+    }
+}
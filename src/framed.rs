@@ -0,0 +1,177 @@
+//! `Stream`/`Sink` adapters that drive `AsyncDecode`/`AsyncEncode` to completion repeatedly.
+
+use std::pin::Pin;
+
+use futures_core::stream::Stream;
+use futures_core::task::{Context, Poll};
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_sink::Sink;
+
+use crate::{AsyncDecode, AsyncEncode, DecodeError, PollDec, PollEnc};
+
+/// Turns an `AsyncDecode` into a `Stream` that yields an unbounded sequence of decoded items.
+///
+/// Each time the inner decoder finishes, a fresh one is created (via `Default`, or via a
+/// user-supplied factory, see `with_factory`) and decoding continues from there.
+pub struct FramedRead<R, D, F> {
+    reader: R,
+    decoder: Option<D>,
+    new_decoder: F,
+}
+
+impl<R, D> FramedRead<R, D, fn() -> D>
+    where D: Default
+{
+    /// Create a `FramedRead`, creating a new `D::default()` whenever a decoder runs to
+    /// completion.
+    pub fn new(reader: R, decoder: D) -> FramedRead<R, D, fn() -> D> {
+        FramedRead::with_factory(reader, decoder, D::default)
+    }
+}
+
+impl<R, D, F> FramedRead<R, D, F>
+    where F: FnMut() -> D
+{
+    /// Create a `FramedRead`, calling `new_decoder` to create the decoder for the next item
+    /// whenever the current decoder runs to completion.
+    pub fn with_factory(reader: R, decoder: D, new_decoder: F) -> FramedRead<R, D, F> {
+        FramedRead {
+            reader,
+            decoder: Some(decoder),
+            new_decoder,
+        }
+    }
+
+    /// Gives back the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R, D, F> Stream for FramedRead<R, D, F>
+    where R: AsyncRead + Unpin,
+          D: AsyncDecode + Unpin,
+          F: FnMut() -> D + Unpin
+{
+    type Item = Result<D::Item, DecodeError<D::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            let decoder = this.decoder
+                .take()
+                .expect("FramedRead polled after its decoder panicked");
+
+            match decoder.poll_decode(cx, &mut this.reader) {
+                PollDec::Done(item, _) => {
+                    this.decoder = Some((this.new_decoder)());
+                    return Poll::Ready(Some(Ok(item)));
+                }
+                PollDec::Progress(next, _) => {
+                    this.decoder = Some(next);
+                    continue;
+                }
+                PollDec::Pending(next) => {
+                    this.decoder = Some(next);
+                    return Poll::Pending;
+                }
+                PollDec::Errored(err) => {
+                    // A decode error discards the failed decoder and reseeds a fresh one, the
+                    // same as after `Done`: one bad frame does not end the stream, and the next
+                    // `poll_next` resumes decoding whatever follows. Mirrors `FramedWrite`, which
+                    // likewise resets to "no encoder in progress" after an encode error.
+                    this.decoder = Some((this.new_decoder)());
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+    }
+}
+
+/// Turns an `AsyncEncode` into a `Sink` that accepts an unbounded sequence of items to encode.
+///
+/// Each time the inner encoder finishes, a fresh one is created (via a user-supplied factory)
+/// from the next item handed to `start_send`.
+pub struct FramedWrite<W, E, F> {
+    writer: W,
+    encoder: Option<E>,
+    new_encoder: F,
+}
+
+impl<W, E, F> FramedWrite<W, E, F> {
+    /// Create a `FramedWrite`, calling `new_encoder` on every item passed to `start_send` to
+    /// obtain the encoder for that item.
+    pub fn new(writer: W, new_encoder: F) -> FramedWrite<W, E, F> {
+        FramedWrite {
+            writer,
+            encoder: None,
+            new_encoder,
+        }
+    }
+
+    /// Gives back the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W, E, F> FramedWrite<W, E, F>
+    where W: AsyncWrite + Unpin,
+          E: AsyncEncode + Unpin
+{
+    fn poll_drain(&mut self, cx: &mut Context) -> Poll<Result<(), futures_io::Error>> {
+        loop {
+            let encoder = match self.encoder.take() {
+                Some(encoder) => encoder,
+                None => return Poll::Ready(Ok(())),
+            };
+
+            match encoder.poll_encode(cx, &mut self.writer) {
+                PollEnc::Done(_) => return Poll::Ready(Ok(())),
+                PollEnc::Progress(next, _) => self.encoder = Some(next),
+                PollEnc::Pending(next) => {
+                    self.encoder = Some(next);
+                    return Poll::Pending;
+                }
+                // `self.encoder` is left `None` here, the same state as after `Done`: an encode
+                // error discards the failed encoder and leaves the sink ready to accept a new
+                // item via `start_send`, rather than poisoning it permanently. Mirrors
+                // `FramedRead`, which likewise reseeds a fresh decoder after a decode error.
+                PollEnc::Errored(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+}
+
+impl<W, E, F, Item> Sink<Item> for FramedWrite<W, E, F>
+    where W: AsyncWrite + Unpin,
+          E: AsyncEncode + Unpin,
+          F: FnMut(Item) -> E + Unpin
+{
+    type Error = futures_io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        debug_assert!(this.encoder.is_none(),
+                      "start_send called without poll_ready reporting readiness first");
+        this.encoder = Some((this.new_encoder)(item));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.writer).poll_close(cx),
+            other => other,
+        }
+    }
+}
@@ -0,0 +1,531 @@
+//! A combinator that prefixes encoded items with their byte length, and uses that length to
+//! bound decoding of the next item.
+
+use std::pin::Pin;
+
+use futures_core::task::{Context, Poll};
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::{AsyncDecode, AsyncEncode, AsyncEncodeLen, DecodeError, PollDec, PollEnc};
+
+/// How many bytes the length header of a `LengthDelimited` frame occupies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderWidth {
+    /// A single byte, supporting frames of up to 255 bytes.
+    One,
+    /// Two bytes, big-endian.
+    Two,
+    /// Four bytes, big-endian.
+    Four,
+    /// Eight bytes, big-endian.
+    Eight,
+}
+
+impl HeaderWidth {
+    fn len(self) -> usize {
+        match self {
+            HeaderWidth::One => 1,
+            HeaderWidth::Two => 2,
+            HeaderWidth::Four => 4,
+            HeaderWidth::Eight => 8,
+        }
+    }
+
+    fn encode(self, len: usize) -> Vec<u8> {
+        match self {
+            HeaderWidth::One => vec![len as u8],
+            HeaderWidth::Two => (len as u16).to_be_bytes().to_vec(),
+            HeaderWidth::Four => (len as u32).to_be_bytes().to_vec(),
+            HeaderWidth::Eight => (len as u64).to_be_bytes().to_vec(),
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> usize {
+        match self {
+            HeaderWidth::One => bytes[0] as usize,
+            HeaderWidth::Two => {
+                let mut buf = [0u8; 2];
+                buf.copy_from_slice(bytes);
+                u16::from_be_bytes(buf) as usize
+            }
+            HeaderWidth::Four => {
+                let mut buf = [0u8; 4];
+                buf.copy_from_slice(bytes);
+                u32::from_be_bytes(buf) as usize
+            }
+            HeaderWidth::Eight => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(bytes);
+                u64::from_be_bytes(buf) as usize
+            }
+        }
+    }
+}
+
+/// An error produced while decoding a `LengthDelimited` frame.
+#[derive(Debug)]
+pub enum LengthDelimitedError<E> {
+    /// The advertised frame length exceeds the configured maximum.
+    FrameTooLong {
+        /// The length read from the frame header.
+        len: usize,
+        /// The configured maximum frame length.
+        max: usize,
+    },
+    /// The inner decoder finished without consuming exactly the advertised number of bytes.
+    /// Reads beyond the advertised length see end of input, so an inner decoder that attempts
+    /// them fails with its own end-of-input error instead.
+    FrameLengthMismatch,
+    /// The inner decoder produced a data error.
+    Inner(E),
+}
+
+/// Caps reads to at most `remaining` bytes, so that an inner decoder asking for more than that
+/// (e.g. one that reads into a buffer sized for the whole item up front) can never pull bytes
+/// belonging to the next frame off the stream.
+struct Bounded<'a, R> {
+    inner: &'a mut R,
+    remaining: usize,
+}
+
+impl<'a, R: AsyncRead + Unpin> AsyncRead for Bounded<'a, R> {
+    fn poll_read(self: Pin<&mut Self>,
+                 cx: &mut Context,
+                 buf: &mut [u8])
+                 -> Poll<Result<usize, futures_io::Error>> {
+        let this = self.get_mut();
+        let max = this.remaining.min(buf.len());
+        let result = Pin::new(&mut *this.inner).poll_read(cx, &mut buf[..max]);
+        if let Poll::Ready(Ok(n)) = &result {
+            this.remaining -= *n;
+        }
+        result
+    }
+}
+
+enum DecodeState<C> {
+    Header { header: Vec<u8>, read: usize, inner: C },
+    Body { len: usize, consumed: usize, inner: C },
+}
+
+enum EncodeState<C> {
+    Header { header: Vec<u8>, written: usize, inner: C },
+    Body(C),
+}
+
+/// Wraps a codec `C`, prefixing encoded items with their byte length and using that length to
+/// bound decoding of the next item, guarding against frames longer than `max_frame_length`.
+pub struct LengthDelimited<C> {
+    width: HeaderWidth,
+    max_frame_length: usize,
+    decode: Option<DecodeState<C>>,
+    encode: Option<EncodeState<C>>,
+}
+
+impl<C> LengthDelimited<C> {
+    /// Wrap `inner`, ready to decode the next frame. `max_frame_length` bounds the length
+    /// advertised by the frame header, not the header itself.
+    pub fn new(inner: C, width: HeaderWidth, max_frame_length: usize) -> LengthDelimited<C> {
+        LengthDelimited {
+            width,
+            max_frame_length,
+            decode: Some(DecodeState::Header {
+                             header: vec![0; width.len()],
+                             read: 0,
+                             inner,
+                         }),
+            encode: None,
+        }
+    }
+
+    /// Wrap `inner`, ready to encode it as a frame of `len` bytes (the exact number of bytes
+    /// `inner` will write, e.g. from `AsyncEncodeLen::remaining_bytes`).
+    pub fn for_encoding(inner: C, width: HeaderWidth, len: usize) -> LengthDelimited<C> {
+        LengthDelimited {
+            width,
+            max_frame_length: usize::MAX,
+            decode: None,
+            encode: Some(EncodeState::Header {
+                             header: width.encode(len),
+                             written: 0,
+                             inner,
+                         }),
+        }
+    }
+}
+
+impl<C> AsyncEncode for LengthDelimited<C>
+    where C: AsyncEncode
+{
+    fn poll_encode<W: AsyncWrite + Unpin>(self, cx: &mut Context, writer: &mut W) -> PollEnc<Self> {
+        let LengthDelimited { width, max_frame_length, decode, encode } = self;
+        let encode = encode.expect("called poll_encode on a LengthDelimited set up for decoding");
+
+        match encode {
+            EncodeState::Header { header, written, inner } => {
+                match Pin::new(&mut *writer).poll_write(cx, &header[written..]) {
+                    Poll::Ready(Ok(0)) => {
+                        PollEnc::Errored(futures_io::Error::new(futures_io::ErrorKind::WriteZero,
+                                                                 "length header write returned 0 bytes"))
+                    }
+                    Poll::Ready(Ok(n)) => {
+                        let written = written + n;
+                        if written == header.len() {
+                            PollEnc::Progress(LengthDelimited {
+                                                   width,
+                                                   max_frame_length,
+                                                   decode,
+                                                   encode: Some(EncodeState::Body(inner)),
+                                               },
+                                               n)
+                        } else {
+                            PollEnc::Progress(LengthDelimited {
+                                                   width,
+                                                   max_frame_length,
+                                                   decode,
+                                                   encode: Some(EncodeState::Header {
+                                                                    header,
+                                                                    written,
+                                                                    inner,
+                                                                }),
+                                               },
+                                               n)
+                        }
+                    }
+                    Poll::Pending => {
+                        PollEnc::Pending(LengthDelimited {
+                                              width,
+                                              max_frame_length,
+                                              decode,
+                                              encode: Some(EncodeState::Header {
+                                                               header,
+                                                               written,
+                                                               inner,
+                                                           }),
+                                          })
+                    }
+                    Poll::Ready(Err(err)) => PollEnc::Errored(err),
+                }
+            }
+            EncodeState::Body(inner) => {
+                match inner.poll_encode(cx, writer) {
+                    PollEnc::Done(n) => PollEnc::Done(n),
+                    PollEnc::Progress(inner, n) => {
+                        PollEnc::Progress(LengthDelimited {
+                                               width,
+                                               max_frame_length,
+                                               decode,
+                                               encode: Some(EncodeState::Body(inner)),
+                                           },
+                                           n)
+                    }
+                    PollEnc::Pending(inner) => {
+                        PollEnc::Pending(LengthDelimited {
+                                              width,
+                                              max_frame_length,
+                                              decode,
+                                              encode: Some(EncodeState::Body(inner)),
+                                          })
+                    }
+                    PollEnc::Errored(err) => PollEnc::Errored(err),
+                }
+            }
+        }
+    }
+}
+
+impl<C> AsyncDecode for LengthDelimited<C>
+    where C: AsyncDecode
+{
+    type Item = C::Item;
+    type Error = LengthDelimitedError<C::Error>;
+
+    fn poll_decode<R: AsyncRead + Unpin>(self,
+                                 cx: &mut Context,
+                                 reader: &mut R)
+                                 -> PollDec<Self::Item, Self, Self::Error> {
+        let LengthDelimited { width, max_frame_length, decode, encode } = self;
+        let decode = decode.expect("called poll_decode on a LengthDelimited set up for encoding");
+
+        match decode {
+            DecodeState::Header { mut header, read, inner } => {
+                match Pin::new(&mut *reader).poll_read(cx, &mut header[read..]) {
+                    Poll::Ready(Ok(0)) => {
+                        PollDec::Errored(DecodeError::ReaderError(futures_io::Error::new(futures_io::ErrorKind::UnexpectedEof, "eof while reading length header")))
+                    }
+                    Poll::Ready(Ok(n)) => {
+                        let read = read + n;
+                        if read == header.len() {
+                            let len = width.decode(&header);
+                            if len > max_frame_length {
+                                PollDec::Errored(DecodeError::DataError(LengthDelimitedError::FrameTooLong {
+                                    len,
+                                    max: max_frame_length,
+                                }))
+                            } else {
+                                PollDec::Progress(LengthDelimited {
+                                                       width,
+                                                       max_frame_length,
+                                                       decode: Some(DecodeState::Body {
+                                                                        len,
+                                                                        consumed: 0,
+                                                                        inner,
+                                                                    }),
+                                                       encode,
+                                                   },
+                                                   n)
+                            }
+                        } else {
+                            PollDec::Progress(LengthDelimited {
+                                                   width,
+                                                   max_frame_length,
+                                                   decode: Some(DecodeState::Header {
+                                                                    header,
+                                                                    read,
+                                                                    inner,
+                                                                }),
+                                                   encode,
+                                               },
+                                               n)
+                        }
+                    }
+                    Poll::Pending => {
+                        PollDec::Pending(LengthDelimited {
+                                              width,
+                                              max_frame_length,
+                                              decode: Some(DecodeState::Header { header, read, inner }),
+                                              encode,
+                                          })
+                    }
+                    Poll::Ready(Err(err)) => PollDec::Errored(DecodeError::ReaderError(err)),
+                }
+            }
+            DecodeState::Body { len, consumed, inner } => {
+                let mut bounded = Bounded { inner: reader, remaining: len - consumed };
+                match inner.poll_decode(cx, &mut bounded) {
+                    PollDec::Done(item, n) => {
+                        if consumed + n != len {
+                            PollDec::Errored(DecodeError::DataError(LengthDelimitedError::FrameLengthMismatch))
+                        } else {
+                            PollDec::Done(item, n)
+                        }
+                    }
+                    PollDec::Progress(inner, n) => {
+                        let consumed = consumed + n;
+                        if consumed > len {
+                            PollDec::Errored(DecodeError::DataError(LengthDelimitedError::FrameLengthMismatch))
+                        } else {
+                            PollDec::Progress(LengthDelimited {
+                                                   width,
+                                                   max_frame_length,
+                                                   decode: Some(DecodeState::Body {
+                                                                    len,
+                                                                    consumed,
+                                                                    inner,
+                                                                }),
+                                                   encode,
+                                               },
+                                               n)
+                        }
+                    }
+                    PollDec::Pending(inner) => {
+                        PollDec::Pending(LengthDelimited {
+                                              width,
+                                              max_frame_length,
+                                              decode: Some(DecodeState::Body { len, consumed, inner }),
+                                              encode,
+                                          })
+                    }
+                    PollDec::Errored(DecodeError::ReaderError(err)) => {
+                        PollDec::Errored(DecodeError::ReaderError(err))
+                    }
+                    PollDec::Errored(DecodeError::DataError(err)) => {
+                        PollDec::Errored(DecodeError::DataError(LengthDelimitedError::Inner(err)))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<C> AsyncEncodeLen for LengthDelimited<C>
+    where C: AsyncEncodeLen
+{
+    fn remaining_bytes(&self) -> usize {
+        match &self.encode {
+            None => 0,
+            Some(EncodeState::Header { header, written, inner }) => {
+                (header.len() - written) + inner.remaining_bytes()
+            }
+            Some(EncodeState::Body(inner)) => inner.remaining_bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    use crate::testing::{test_codec, Schedule};
+
+    /// Encodes a fixed `Vec<u8>` verbatim.
+    struct BytesEncoder {
+        data: Vec<u8>,
+        written: usize,
+    }
+
+    impl AsyncEncode for BytesEncoder {
+        fn poll_encode<W: AsyncWrite + Unpin>(mut self, cx: &mut Context, writer: &mut W) -> PollEnc<Self> {
+            match Pin::new(writer).poll_write(cx, &self.data[self.written..]) {
+                Poll::Ready(Ok(0)) => {
+                    PollEnc::Errored(futures_io::Error::new(futures_io::ErrorKind::WriteZero,
+                                                             "BytesEncoder: writer returned 0 bytes"))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.written += n;
+                    if self.written == self.data.len() {
+                        PollEnc::Done(n)
+                    } else {
+                        PollEnc::Progress(self, n)
+                    }
+                }
+                Poll::Pending => PollEnc::Pending(self),
+                Poll::Ready(Err(err)) => PollEnc::Errored(err),
+            }
+        }
+    }
+
+    /// Decodes exactly `remaining` bytes into a `Vec<u8>`.
+    struct BytesDecoder {
+        remaining: usize,
+        buf: Vec<u8>,
+    }
+
+    impl AsyncDecode for BytesDecoder {
+        type Item = Vec<u8>;
+        type Error = ();
+
+        fn poll_decode<R: AsyncRead + Unpin>(mut self,
+                                             cx: &mut Context,
+                                             reader: &mut R)
+                                             -> PollDec<Self::Item, Self, Self::Error> {
+            let mut chunk = vec![0; self.remaining];
+            match Pin::new(reader).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => {
+                    PollDec::Errored(DecodeError::ReaderError(futures_io::Error::new(futures_io::ErrorKind::UnexpectedEof, "BytesDecoder: eof")))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    self.remaining -= n;
+                    if self.remaining == 0 {
+                        PollDec::Done(self.buf, n)
+                    } else {
+                        PollDec::Progress(self, n)
+                    }
+                }
+                Poll::Pending => PollDec::Pending(self),
+                Poll::Ready(Err(err)) => PollDec::Errored(DecodeError::ReaderError(err)),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_under_adversarial_scheduling() {
+        let data = b"hello".to_vec();
+        let len = data.len();
+
+        test_codec(data,
+                   |item| {
+                       LengthDelimited::for_encoding(BytesEncoder { data: item, written: 0 },
+                                                      HeaderWidth::Two,
+                                                      len)
+                   },
+                   LengthDelimited::new(BytesDecoder {
+                                            remaining: len,
+                                            buf: Vec::new(),
+                                        },
+                                        HeaderWidth::Two,
+                                        1024),
+                   Schedule::new(vec![true, false, false], 2),
+                   Schedule::one_byte_at_a_time());
+    }
+
+    /// Decodes exactly `size` bytes, regardless of how short the current frame actually is - the
+    /// same "ask for the whole value up front" shape as an inner decoder unaware of the length
+    /// prefix wrapped around it.
+    struct OverReadingDecoder {
+        size: usize,
+        buf: Vec<u8>,
+    }
+
+    impl AsyncDecode for OverReadingDecoder {
+        type Item = Vec<u8>;
+        type Error = ();
+
+        fn poll_decode<R: AsyncRead + Unpin>(mut self,
+                                             cx: &mut Context,
+                                             reader: &mut R)
+                                             -> PollDec<Self::Item, Self, Self::Error> {
+            let mut chunk = vec![0; self.size - self.buf.len()];
+            match Pin::new(reader).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => {
+                    PollDec::Errored(DecodeError::ReaderError(futures_io::Error::new(futures_io::ErrorKind::UnexpectedEof, "OverReadingDecoder: eof")))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    if self.buf.len() == self.size {
+                        PollDec::Done(self.buf, n)
+                    } else {
+                        PollDec::Progress(self, n)
+                    }
+                }
+                Poll::Pending => PollDec::Pending(self),
+                Poll::Ready(Err(err)) => PollDec::Errored(DecodeError::ReaderError(err)),
+            }
+        }
+    }
+
+    fn noop_waker() -> Waker {
+        fn no_op(_: *const ()) {}
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn raw_waker() -> RawWaker {
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+        unsafe { Waker::from_raw(raw_waker()) }
+    }
+
+    #[test]
+    fn body_decode_does_not_read_past_the_frame_boundary() {
+        // Two back-to-back 2-byte frames, each headed by a 1-byte length.
+        let stream = vec![2u8, b'h', b'i', 2u8, b'y', b'o'];
+
+        let decoder = LengthDelimited::new(OverReadingDecoder { size: 4, buf: Vec::new() },
+                                            HeaderWidth::One,
+                                            1024);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut reader = &stream[..];
+
+        let mut decoder = Some(decoder);
+        let result = loop {
+            match decoder.take().unwrap().poll_decode(&mut cx, &mut reader) {
+                PollDec::Done(item, _) => break Ok(item),
+                PollDec::Progress(next, _) => decoder = Some(next),
+                PollDec::Pending(next) => decoder = Some(next),
+                PollDec::Errored(err) => break Err(err),
+            }
+        };
+
+        assert!(result.is_err(),
+                "an inner decoder reading past the advertised frame length must not silently \
+                 succeed by consuming bytes belonging to the next frame");
+        // Only the first frame's 2 body bytes (plus its 1-byte header) may have been consumed;
+        // the second frame's header byte must still be sitting unread in the stream.
+        assert_eq!(reader[0], 2u8,
+                   "the second frame's bytes must not have been consumed by the first decode");
+    }
+}
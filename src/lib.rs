@@ -3,6 +3,22 @@
 
 extern crate futures_core;
 extern crate futures_io;
+extern crate futures_sink;
+
+mod buffered;
+mod chain;
+mod combinators;
+mod framed;
+mod length_delimited;
+mod push_decode;
+pub mod testing;
+
+pub use buffered::Buffered;
+pub use chain::Chain;
+pub use combinators::{encode_map, AndThen, EncodeMap, Map, MapErr};
+pub use framed::{FramedRead, FramedWrite};
+pub use length_delimited::{HeaderWidth, LengthDelimited, LengthDelimitedError};
+pub use push_decode::{FromPushDecode, PushDec, PushDecode, ToPushDecode};
 
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
@@ -35,7 +51,11 @@ pub trait AsyncEncode
     ///
     /// If `writer.poll_write` returns `Ok(Ready(0))` even though the value has not been fully
     /// encoded, this must return an error of kind `WriteZero`.
-    fn poll_encode<W: AsyncWrite>(self, cx: &mut Context, writer: &mut W) -> PollEnc<Self>;
+    ///
+    /// `W` is required to be `Unpin` so that implementations can call `poll_write` through a
+    /// plain `&mut W` (via `Pin::new`) instead of having to pin-project an arbitrary caller-
+    /// supplied writer.
+    fn poll_encode<W: AsyncWrite + Unpin>(self, cx: &mut Context, writer: &mut W) -> PollEnc<Self>;
 }
 
 /// An `AsyncEncode` that can precompute how many bytes of encoded data it produces.
@@ -75,10 +95,39 @@ pub trait AsyncDecode
     ///
     /// If `reader.poll_read` returns `Ok(Ready(0))` even though the value has not been fully
     /// decoded, this must return an error of kind `UnexpectedEof`.
-    fn poll_decode<R: AsyncRead>(self,
+    ///
+    /// `R` is required to be `Unpin` so that implementations can call `poll_read` through a
+    /// plain `&mut R` (via `Pin::new`) instead of having to pin-project an arbitrary caller-
+    /// supplied reader.
+    fn poll_decode<R: AsyncRead + Unpin>(self,
                                  cx: &mut Context,
                                  reader: &mut R)
                                  -> PollDec<Self::Item, Self, Self::Error>;
+
+    /// Transform the decoded item by applying `f` to it once decoding is done.
+    fn map<F, U>(self, f: F) -> combinators::Map<Self, F>
+        where F: FnOnce(Self::Item) -> U,
+              Self: Sized
+    {
+        combinators::Map::new(self, f)
+    }
+
+    /// Transform the `DataError` produced by this decoder by applying `g` to it.
+    fn map_err<G, U>(self, g: G) -> combinators::MapErr<Self, G>
+        where G: FnOnce(Self::Error) -> U,
+              Self: Sized
+    {
+        combinators::MapErr::new(self, g)
+    }
+
+    /// Run a second decoder, obtained from this decoder's item via `f`, over the same reader.
+    fn and_then<F, D2>(self, f: F) -> combinators::AndThen<Self, F, D2>
+        where F: FnOnce(Self::Item) -> D2,
+              D2: AsyncDecode<Error = Self::Error>,
+              Self: Sized
+    {
+        combinators::AndThen::new(self, f)
+    }
 }
 
 /// An error that occured during decoding.
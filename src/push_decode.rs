@@ -0,0 +1,330 @@
+//! A push-based counterpart to `AsyncDecode`, for sources that cannot be modeled as an
+//! `AsyncRead` (event callbacks, message-oriented transports, datagram arrivals).
+
+use std::pin::Pin;
+
+use futures_core::task::{Context, Poll};
+use futures_io::AsyncRead;
+
+use crate::{AsyncDecode, DecodeError, PollDec};
+
+/// The return value of `PushDecode::push`.
+pub enum PushDec<T, S, E> {
+    /// Decoding is done, yielding an item of type `T`. The second value is how many bytes of
+    /// the input given to `push` were consumed.
+    Done(T, usize),
+    /// Decoding is not done yet; more input is needed. The second value is how many bytes of
+    /// the input given to `push` were consumed.
+    More(S, usize),
+    /// The input could not be decoded into a value.
+    Errored(E),
+}
+
+/// A trait for types that can be decoded by repeatedly pushing byte slices into them, rather
+/// than by pulling from an `AsyncRead`.
+pub trait PushDecode
+    where Self: Sized
+{
+    /// The type of the value to decode.
+    type Item;
+    /// An error indicating how decoding can fail.
+    type Error;
+
+    /// Feed `input` into the decoder. Returns how many bytes of `input` were consumed (which may
+    /// be less than `input.len()`), and either the decoded item, a resumed decoder expecting
+    /// more input, or an error.
+    fn push(self, input: &[u8]) -> PushDec<Self::Item, Self, Self::Error>;
+}
+
+/// Drives a `PushDecode` from an `AsyncRead`, by reading into a small internal buffer and
+/// feeding it to the pushed decoder.
+pub struct FromPushDecode<D> {
+    inner: Option<D>,
+    buf: [u8; 1024],
+    /// Bytes already read from the reader in a previous call that `inner` did not consume
+    /// (because `push` stopped short of the full slice while still needing more input); fed to
+    /// `inner` again before any further reading. Bytes that `inner` leaves unconsumed when it
+    /// completes an item are discarded.
+    leftover: Vec<u8>,
+}
+
+impl<D> FromPushDecode<D> {
+    /// Wrap `inner`, which will be driven by reading from an `AsyncRead`.
+    pub fn new(inner: D) -> FromPushDecode<D> {
+        FromPushDecode {
+            inner: Some(inner),
+            buf: [0; 1024],
+            leftover: Vec::new(),
+        }
+    }
+}
+
+impl<D> AsyncDecode for FromPushDecode<D>
+    where D: PushDecode
+{
+    type Item = D::Item;
+    type Error = D::Error;
+
+    fn poll_decode<R: AsyncRead + Unpin>(mut self,
+                                 cx: &mut Context,
+                                 reader: &mut R)
+                                 -> PollDec<Self::Item, Self, Self::Error> {
+        let inner = self.inner.take().expect("polled FromPushDecode after completion");
+
+        // Bytes left over from a previous read must be replayed before reading anything new, or
+        // they would be lost for good (the reader already handed them over and can't be asked
+        // for them again). Since no `poll_read` happens in this case, the number of bytes read
+        // from `reader` in this call is honestly 0.
+        if !self.leftover.is_empty() {
+            let leftover = std::mem::replace(&mut self.leftover, Vec::new());
+            return match inner.push(&leftover) {
+                PushDec::Done(item, _) => PollDec::Done(item, 0),
+                PushDec::More(inner, consumed) => {
+                    self.leftover = leftover[consumed..].to_vec();
+                    self.inner = Some(inner);
+                    PollDec::Progress(self, 0)
+                }
+                PushDec::Errored(err) => PollDec::Errored(DecodeError::DataError(err)),
+            };
+        }
+
+        match Pin::new(reader).poll_read(cx, &mut self.buf) {
+            Poll::Ready(Ok(0)) => {
+                PollDec::Errored(DecodeError::ReaderError(futures_io::Error::new(futures_io::ErrorKind::UnexpectedEof, "eof while driving a PushDecode")))
+            }
+            Poll::Ready(Ok(n)) => {
+                // `n` is how many bytes were actually read from `reader` in this call, which may
+                // be more than `inner` consumed (it is free to stop early, e.g. once it has a
+                // full item's worth of input). That's the number this must report, per the
+                // `AsyncDecode::poll_decode` contract - not `inner`'s internal consumption.
+                match inner.push(&self.buf[..n]) {
+                    PushDec::Done(item, _) => PollDec::Done(item, n),
+                    PushDec::More(inner, consumed) => {
+                        self.leftover = self.buf[consumed..n].to_vec();
+                        self.inner = Some(inner);
+                        PollDec::Progress(self, n)
+                    }
+                    PushDec::Errored(err) => PollDec::Errored(DecodeError::DataError(err)),
+                }
+            }
+            Poll::Pending => {
+                self.inner = Some(inner);
+                PollDec::Pending(self)
+            }
+            Poll::Ready(Err(err)) => PollDec::Errored(DecodeError::ReaderError(err)),
+        }
+    }
+}
+
+/// Drives any `AsyncDecode` as a `PushDecode`, by buffering pushed bytes behind an in-memory
+/// reader.
+pub struct ToPushDecode<D> {
+    inner: D,
+}
+
+impl<D> ToPushDecode<D> {
+    /// Wrap `inner`, which will be driven by pushing byte slices into it.
+    pub fn new(inner: D) -> ToPushDecode<D> {
+        ToPushDecode { inner }
+    }
+}
+
+impl<D> PushDecode for ToPushDecode<D>
+    where D: AsyncDecode
+{
+    type Item = D::Item;
+    type Error = D::Error;
+
+    fn push(self, input: &[u8]) -> PushDec<Self::Item, Self, Self::Error> {
+        // A plain `&[u8]` reader reports running out of bytes as EOF, which `D` would then
+        // have to turn into an `UnexpectedEof` error (per the `AsyncDecode` contract). But
+        // running out of *pushed* bytes isn't EOF, it just means the caller needs to `push`
+        // more later - exactly what `Pending` means. `InputBuf` reports that instead, so a
+        // decoder that reads across several internal steps in one `poll_decode` call (e.g.
+        // `AndThen`, for tag-then-payload protocols) resumes correctly instead of erroring out
+        // when a step boundary lands exactly on the end of `input`.
+        let mut reader = InputBuf { buf: input, consumed: 0 };
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        match self.inner.poll_decode(&mut cx, &mut reader) {
+            PollDec::Done(item, n) => PushDec::Done(item, n),
+            PollDec::Progress(inner, n) => PushDec::More(ToPushDecode { inner }, n),
+            PollDec::Pending(inner) => PushDec::More(ToPushDecode { inner }, reader.consumed),
+            PollDec::Errored(DecodeError::DataError(err)) => PushDec::Errored(err),
+            PollDec::Errored(DecodeError::ReaderError(_)) => {
+                unreachable!("InputBuf never returns an error or an EOF-signaling Ok(0)")
+            }
+        }
+    }
+}
+
+/// An in-memory reader over a pushed byte slice that reports running out of bytes as `Pending`
+/// rather than EOF, since more bytes may be pushed later.
+struct InputBuf<'a> {
+    buf: &'a [u8],
+    consumed: usize,
+}
+
+impl<'a> AsyncRead for InputBuf<'a> {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, futures_io::Error>> {
+        let this = self.get_mut();
+        let remaining = &this.buf[this.consumed..];
+        if remaining.is_empty() {
+            return Poll::Pending;
+        }
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        this.consumed += n;
+        Poll::Ready(Ok(n))
+    }
+}
+
+fn noop_waker() -> std::task::Waker {
+    fn no_op(_: *const ()) {}
+    fn clone(_: *const ()) -> std::task::RawWaker {
+        raw_waker()
+    }
+    fn raw_waker() -> std::task::RawWaker {
+        static VTABLE: std::task::RawWakerVTable = std::task::RawWakerVTable::new(clone, no_op, no_op, no_op);
+        std::task::RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    unsafe { std::task::Waker::from_raw(raw_waker()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tiny length-prefixed `PushDecode`: a single header byte giving the body length,
+    /// followed by that many body bytes. Each `push` call only consumes what it currently
+    /// wants (the header byte, or however much of the body it still needs), regardless of how
+    /// much more input it was handed - the same shape as any decoder driven by `FromPushDecode`.
+    enum TinyPushDecoder {
+        Header,
+        Body { len: usize, buf: Vec<u8> },
+    }
+
+    impl PushDecode for TinyPushDecoder {
+        type Item = Vec<u8>;
+        type Error = ();
+
+        fn push(self, input: &[u8]) -> PushDec<Self::Item, Self, Self::Error> {
+            match self {
+                TinyPushDecoder::Header => {
+                    if input.is_empty() {
+                        PushDec::More(TinyPushDecoder::Header, 0)
+                    } else {
+                        let len = input[0] as usize;
+                        PushDec::More(TinyPushDecoder::Body { len, buf: Vec::new() }, 1)
+                    }
+                }
+                TinyPushDecoder::Body { len, mut buf } => {
+                    let take = (len - buf.len()).min(input.len());
+                    buf.extend_from_slice(&input[..take]);
+                    if buf.len() == len {
+                        PushDec::Done(buf, take)
+                    } else {
+                        PushDec::More(TinyPushDecoder::Body { len, buf }, take)
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn from_push_decode_retains_bytes_consumed_ahead_of_the_current_step() {
+        // A single read delivers the 1-byte header, both body bytes, and one extra byte
+        // belonging to whatever comes after this item - all in one go. `push` only consumes
+        // the header byte on the first call, so the body bytes (and the trailing extra byte)
+        // must not be thrown away before the second call processes them.
+        let stream = vec![2u8, b'A', b'B', b'C'];
+        let mut reader = &stream[..];
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut decoder = Some(FromPushDecode::new(TinyPushDecoder::Header));
+        let mut total_read = 0;
+        let item = loop {
+            match decoder.take().unwrap().poll_decode(&mut cx, &mut reader) {
+                PollDec::Done(item, n) => {
+                    total_read += n;
+                    break item;
+                }
+                PollDec::Progress(next, n) => {
+                    total_read += n;
+                    decoder = Some(next);
+                }
+                PollDec::Pending(next) => decoder = Some(next),
+                PollDec::Errored(err) => panic!("unexpected decode error: {:?}", err),
+            }
+        };
+
+        assert_eq!(item, vec![b'A', b'B'],
+                   "the body bytes read ahead of time by the first poll_read must not be lost");
+        // All 4 bytes were actually pulled off `reader` in the very first call (the internal
+        // buffer is much larger than this input), so the reported total must account for all
+        // of them, not just the 2 bytes `push` logically consumed towards this item.
+        assert_eq!(total_read, 4);
+    }
+
+    /// Decodes exactly `remaining` bytes into a `Vec<u8>`.
+    struct BytesDecoder {
+        remaining: usize,
+        buf: Vec<u8>,
+    }
+
+    impl AsyncDecode for BytesDecoder {
+        type Item = Vec<u8>;
+        type Error = ();
+
+        fn poll_decode<R: AsyncRead + Unpin>(mut self,
+                                             cx: &mut Context,
+                                             reader: &mut R)
+                                             -> PollDec<Self::Item, Self, Self::Error> {
+            let mut chunk = vec![0; self.remaining];
+            match Pin::new(reader).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => {
+                    PollDec::Errored(DecodeError::ReaderError(futures_io::Error::new(futures_io::ErrorKind::UnexpectedEof, "BytesDecoder: eof")))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    self.remaining -= n;
+                    if self.remaining == 0 {
+                        PollDec::Done(self.buf, n)
+                    } else {
+                        PollDec::Progress(self, n)
+                    }
+                }
+                Poll::Pending => PollDec::Pending(self),
+                Poll::Ready(Err(err)) => PollDec::Errored(DecodeError::ReaderError(err)),
+            }
+        }
+    }
+
+    #[test]
+    fn from_push_decode_of_to_push_decode_round_trips() {
+        // Going `AsyncDecode -> ToPushDecode -> FromPushDecode` should behave just like the
+        // plain `AsyncDecode` did, end to end.
+        let data = b"hello".to_vec();
+        let len = data.len();
+        let mut reader = &data[..];
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut decoder = Some(FromPushDecode::new(ToPushDecode::new(BytesDecoder {
+                                                                           remaining: len,
+                                                                           buf: Vec::new(),
+                                                                       })));
+        let item = loop {
+            match decoder.take().unwrap().poll_decode(&mut cx, &mut reader) {
+                PollDec::Done(item, _) => break item,
+                PollDec::Progress(next, _) => decoder = Some(next),
+                PollDec::Pending(next) => decoder = Some(next),
+                PollDec::Errored(err) => panic!("unexpected decode error: {:?}", err),
+            }
+        };
+
+        assert_eq!(item, data);
+    }
+}
@@ -0,0 +1,298 @@
+//! A conformance-testing harness for `AsyncEncode`/`AsyncDecode` pairs.
+//!
+//! It round-trips a value through adversarial I/O scheduling (arbitrary `Pending` results,
+//! arbitrarily small reads/writes) to check that a codec's resumable, one-poll-per-call
+//! contract holds regardless of how the underlying reader/writer happens to schedule progress.
+
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::task::{RawWaker, RawWakerVTable, Waker};
+
+use futures_core::task::{Context, Poll};
+use futures_io::{AsyncRead, AsyncWrite, Error as FutIoErr};
+
+use crate::{AsyncDecode, AsyncEncode, PollDec, PollEnc};
+
+/// A schedule of artificial `Pending` results and a cap on how many bytes are let through per
+/// successful poll, used to drive a reader or writer adversarially.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pending: Vec<bool>,
+    position: usize,
+    max_chunk: usize,
+}
+
+impl Schedule {
+    /// Create a `Schedule`. `pending` is consumed (and then cycled) one entry per poll: `true`
+    /// makes that poll return `Pending` instead of progressing. `max_chunk` caps how many bytes
+    /// a successful poll is allowed to read/write.
+    pub fn new(pending: Vec<bool>, max_chunk: usize) -> Schedule {
+        assert!(max_chunk > 0, "max_chunk must be at least 1");
+        Schedule {
+            pending,
+            position: 0,
+            max_chunk,
+        }
+    }
+
+    /// A schedule that never returns `Pending` and only ever lets a single byte through per
+    /// poll, the most adversarial chunking for an otherwise cooperative reader/writer.
+    pub fn one_byte_at_a_time() -> Schedule {
+        Schedule::new(vec![false], 1)
+    }
+
+    fn next_is_pending(&mut self) -> bool {
+        if self.pending.is_empty() {
+            false
+        } else {
+            let is_pending = self.pending[self.position % self.pending.len()];
+            self.position += 1;
+            is_pending
+        }
+    }
+}
+
+/// Wraps an `AsyncRead`, returning `Pending` and truncating reads according to a `Schedule`.
+pub struct ThrottledReader<R> {
+    inner: R,
+    schedule: Schedule,
+}
+
+impl<R> ThrottledReader<R> {
+    /// Wrap `inner`, throttling it according to `schedule`.
+    pub fn new(inner: R, schedule: Schedule) -> ThrottledReader<R> {
+        ThrottledReader { inner, schedule }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, FutIoErr>> {
+        let this = self.get_mut();
+
+        if this.schedule.next_is_pending() {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let max = this.schedule.max_chunk.min(buf.len());
+        Pin::new(&mut this.inner).poll_read(cx, &mut buf[..max])
+    }
+}
+
+/// Wraps an `AsyncWrite`, returning `Pending` and truncating writes according to a `Schedule`.
+pub struct ThrottledWriter<W> {
+    inner: W,
+    schedule: Schedule,
+}
+
+impl<W> ThrottledWriter<W> {
+    /// Wrap `inner`, throttling it according to `schedule`.
+    pub fn new(inner: W, schedule: Schedule) -> ThrottledWriter<W> {
+        ThrottledWriter { inner, schedule }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ThrottledWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize, FutIoErr>> {
+        let this = self.get_mut();
+
+        if this.schedule.next_is_pending() {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let max = this.schedule.max_chunk.min(buf.len());
+        Pin::new(&mut this.inner).poll_write(cx, &buf[..max])
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), FutIoErr>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), FutIoErr>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+/// Round-trips `item` through `make_encoder` and `decoder` over throttled in-memory I/O,
+/// asserting that the decoded value equals `item` and that the byte counts reported by
+/// `poll_encode`/`poll_decode` sum to the true encoded length, no matter how `read_ops` and
+/// `write_ops` interleave `Pending` results and chunk sizes into the process.
+///
+/// Panics on any encode/decode error, or if the reported byte counts or the round-tripped value
+/// do not match.
+pub fn test_codec<E, D>(item: D::Item,
+                         make_encoder: impl FnOnce(D::Item) -> E,
+                         decoder: D,
+                         read_ops: Schedule,
+                         write_ops: Schedule)
+    where E: AsyncEncode,
+          D: AsyncDecode,
+          D::Item: Clone + PartialEq + Debug,
+          D::Error: Debug
+{
+    let expected = item.clone();
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    let mut buf = Vec::new();
+    let mut writer = ThrottledWriter::new(VecWriter(&mut buf), write_ops);
+    let mut total_written = 0;
+    let mut encoder = Some(make_encoder(item));
+    loop {
+        match encoder.take().unwrap().poll_encode(&mut cx, &mut writer) {
+            PollEnc::Done(n) => {
+                total_written += n;
+                break;
+            }
+            PollEnc::Progress(next, n) => {
+                total_written += n;
+                encoder = Some(next);
+            }
+            PollEnc::Pending(next) => encoder = Some(next),
+            PollEnc::Errored(err) => panic!("test_codec: encoding failed: {}", err),
+        }
+    }
+    assert_eq!(total_written,
+               buf.len(),
+               "test_codec: poll_encode reported {} bytes written, but {} bytes ended up in the \
+                writer",
+               total_written,
+               buf.len());
+
+    let mut reader = ThrottledReader::new(&buf[..], read_ops);
+    let mut total_read = 0;
+    let mut decoder = Some(decoder);
+    loop {
+        match decoder.take().unwrap().poll_decode(&mut cx, &mut reader) {
+            PollDec::Done(got, n) => {
+                total_read += n;
+                assert_eq!(got, expected, "test_codec: decoded value did not match the original");
+                break;
+            }
+            PollDec::Progress(next, n) => {
+                total_read += n;
+                decoder = Some(next);
+            }
+            PollDec::Pending(next) => decoder = Some(next),
+            PollDec::Errored(err) => panic!("test_codec: decoding failed: {:?}", err),
+        }
+    }
+    assert_eq!(total_read,
+               buf.len(),
+               "test_codec: poll_decode reported {} bytes read, but the encoded value was {} \
+                bytes long",
+               total_read,
+               buf.len());
+}
+
+struct VecWriter<'a>(&'a mut Vec<u8>);
+
+impl<'a> AsyncWrite for VecWriter<'a> {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context, buf: &[u8]) -> Poll<Result<usize, FutIoErr>> {
+        self.get_mut().0.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), FutIoErr>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), FutIoErr>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn noop_waker() -> Waker {
+    fn no_op(_: *const ()) {}
+    fn clone(_: *const ()) -> RawWaker {
+        raw_waker()
+    }
+    fn raw_waker() -> RawWaker {
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    unsafe { Waker::from_raw(raw_waker()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Buffered;
+
+    /// Encodes a fixed `Vec<u8>` verbatim.
+    struct BytesEncoder {
+        data: Vec<u8>,
+        written: usize,
+    }
+
+    impl AsyncEncode for BytesEncoder {
+        fn poll_encode<W: AsyncWrite + Unpin>(mut self, cx: &mut Context, writer: &mut W) -> PollEnc<Self> {
+            match Pin::new(writer).poll_write(cx, &self.data[self.written..]) {
+                Poll::Ready(Ok(0)) => {
+                    PollEnc::Errored(FutIoErr::new(futures_io::ErrorKind::WriteZero,
+                                                    "BytesEncoder: writer returned 0 bytes"))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.written += n;
+                    if self.written == self.data.len() {
+                        PollEnc::Done(n)
+                    } else {
+                        PollEnc::Progress(self, n)
+                    }
+                }
+                Poll::Pending => PollEnc::Pending(self),
+                Poll::Ready(Err(err)) => PollEnc::Errored(err),
+            }
+        }
+    }
+
+    /// Decodes exactly `remaining` bytes into a `Vec<u8>`.
+    struct BytesDecoder {
+        remaining: usize,
+        buf: Vec<u8>,
+    }
+
+    impl AsyncDecode for BytesDecoder {
+        type Item = Vec<u8>;
+        type Error = ();
+
+        fn poll_decode<R: AsyncRead + Unpin>(mut self,
+                                             cx: &mut Context,
+                                             reader: &mut R)
+                                             -> PollDec<Self::Item, Self, Self::Error> {
+            let mut chunk = vec![0; self.remaining];
+            match Pin::new(reader).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => {
+                    PollDec::Errored(crate::DecodeError::ReaderError(FutIoErr::new(futures_io::ErrorKind::UnexpectedEof, "BytesDecoder: eof")))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    self.remaining -= n;
+                    if self.remaining == 0 {
+                        PollDec::Done(self.buf, n)
+                    } else {
+                        PollDec::Progress(self, n)
+                    }
+                }
+                Poll::Pending => PollDec::Pending(self),
+                Poll::Ready(Err(err)) => PollDec::Errored(crate::DecodeError::ReaderError(err)),
+            }
+        }
+    }
+
+    #[test]
+    fn buffered_round_trips_under_adversarial_scheduling() {
+        let data = b"the quick brown fox jumps over the lazy dog".to_vec();
+        let len = data.len();
+
+        test_codec(data,
+                   |item| Buffered::new(BytesEncoder { data: item, written: 0 }, 3),
+                   BytesDecoder {
+                       remaining: len,
+                       buf: Vec::new(),
+                   },
+                   Schedule::new(vec![true, false, false], 2),
+                   Schedule::one_byte_at_a_time());
+    }
+}